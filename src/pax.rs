@@ -0,0 +1,161 @@
+use std::path::{Path, PathBuf};
+
+use tar::{EntryType, Header};
+
+use crate::int;
+use crate::TAR_BLOCK_SIZE;
+
+/// Largest size representable in a ustar header's octal size field.
+/// Beyond this a `size` PAX record must carry the real value.
+pub const USTAR_MAX_SIZE: u64 = 0o77777777777;
+
+/// A PAX extended header block (typeflag `'x'`) plus the record body that
+/// follows it, ready to be spliced into the segment list immediately before
+/// the ustar header it describes.
+pub struct ExtendedHeader {
+    pub header: Vec<u8>,
+    pub body: Vec<u8>,
+    pub padding: u64,
+}
+
+/// Builds a typeflag-`'x'` PAX extended header and its record body from a
+/// list of `(keyword, value)` pairs.
+pub fn build(records: &[(&str, String)]) -> ExtendedHeader {
+    let body = encode_records(records);
+    let body_len = int::usize_to_u64(body.len());
+
+    let mut header = Header::new_ustar();
+    header.set_entry_type(EntryType::XHeader);
+    header.set_size(body_len);
+    header.set_cksum();
+
+    let size_modulo_block = body_len % TAR_BLOCK_SIZE;
+
+    let padding = if size_modulo_block > 0 {
+        TAR_BLOCK_SIZE - size_modulo_block
+    } else {
+        0
+    };
+
+    ExtendedHeader {
+        header: header.as_bytes().to_vec(),
+        body,
+        padding,
+    }
+}
+
+fn encode_records(records: &[(&str, String)]) -> Vec<u8> {
+    let mut body = Vec::new();
+
+    for (keyword, value) in records {
+        body.extend_from_slice(&encode_record(keyword, value));
+    }
+
+    body
+}
+
+// a record is "<len> <keyword>=<value>\n", where <len> is the decimal byte
+// length of the whole record including itself. since appending digits to
+// <len> can push it into the next digit width, recompute until it settles.
+fn encode_record(keyword: &str, value: &str) -> Vec<u8> {
+    let suffix = format!(" {}={}\n", keyword, value);
+
+    let mut len = suffix.len() + 1;
+
+    loop {
+        let candidate = len.to_string().len() + suffix.len();
+
+        if candidate == len {
+            break;
+        }
+
+        len = candidate;
+    }
+
+    format!("{}{}", len, suffix).into_bytes()
+}
+
+/// A best-effort ustar `name` (or `linkname`) to fall back to when the real
+/// value doesn't fit the ustar header's 100-byte fields. The real value is
+/// recorded in a PAX record (`path` or `linkpath`), so this only needs to be
+/// a harmless placeholder for readers that don't understand PAX extensions.
+///
+/// The ustar fields are a byte limit, not a character limit, so this keeps
+/// the last 100 *bytes* of the lossy path (rounded forward to a UTF-8
+/// character boundary) rather than the last 100 chars, which could overrun
+/// the field by several bytes for non-ASCII paths.
+pub fn ustar_fallback_path(path: &Path) -> PathBuf {
+    let lossy = path.to_string_lossy();
+    let bytes = lossy.as_bytes();
+
+    let window_start = bytes.len().saturating_sub(100);
+
+    let start = (window_start..=bytes.len())
+        .find(|&i| lossy.is_char_boundary(i))
+        .unwrap_or(bytes.len());
+
+    // a window starting on a separator would read as an absolute path,
+    // which the ustar header refuses
+    let truncated = lossy[start..].trim_start_matches('/');
+
+    if truncated.is_empty() {
+        PathBuf::from("pax-path-overflow")
+    } else {
+        PathBuf::from(truncated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_record_length_prefix_is_self_consistent() {
+        let record = encode_record("path", "short");
+        let text = String::from_utf8(record).unwrap();
+
+        let (len_str, rest) = text.split_once(' ').unwrap();
+        let len: usize = len_str.parse().unwrap();
+
+        assert_eq!(len, text.len());
+        assert_eq!(rest, "path=short\n");
+    }
+
+    #[test]
+    fn encode_record_recomputes_length_when_digit_width_grows() {
+        // long enough that including the length prefix's own digit count
+        // pushes the prefix itself into the next digit width
+        let value = "x".repeat(91);
+        let record = encode_record("path", &value);
+        let text = String::from_utf8(record).unwrap();
+
+        let len: usize = text.split_once(' ').unwrap().0.parse().unwrap();
+
+        assert_eq!(len, text.len());
+    }
+
+    #[test]
+    fn build_round_trips_records_through_header_and_body() {
+        let records = [("path", "a/very/long/path".to_string())];
+        let extended = build(&records);
+
+        assert_eq!(extended.header.len(), 512);
+        assert_eq!(extended.body, encode_records(&records));
+        assert_eq!(
+            (int::usize_to_u64(extended.body.len()) + extended.padding) % TAR_BLOCK_SIZE,
+            0,
+        );
+    }
+
+    #[test]
+    fn ustar_fallback_path_truncates_by_bytes_not_chars() {
+        // 100 non-ASCII chars, each 2 bytes in UTF-8 - truncating by char
+        // count alone would produce a 200-byte fallback that doesn't fit
+        let long_name: String = "é".repeat(100);
+
+        let fallback = ustar_fallback_path(Path::new(&long_name));
+
+        assert!(fallback.as_os_str().len() <= 100);
+        assert!(!fallback.as_os_str().is_empty());
+    }
+}
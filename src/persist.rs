@@ -0,0 +1,279 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::int;
+
+/// On-disk format for a persisted `Index`, so a long-lived server can scan a
+/// tree once and reload the result cheaply instead of re-walking and
+/// re-opening every file on every start.
+///
+/// Layout:
+///
+/// ```text
+/// magic            [u8; 4]   b"rtix"
+/// version          u32 LE
+/// entry_count      u64 LE
+/// total_size       u64 LE    sum of the virtual tar's segment sizes
+/// path_len_width   u8        1, 2, 4 or 8 - width of each path length below
+///
+/// entry_count * {
+///     offset        u64 LE    cumulative byte offset of this entry's segments
+///     header        [u8; 512] precomputed ustar header bytes
+///     pax_len       u64 LE    byte length of the PAX extension blob (0 if none)
+///     extent_count  u64 LE    number of content extents below
+/// }
+///
+/// entry_count * {
+///     path_len     path_len_width bytes LE
+///     path         path_len bytes, relative to the index root
+///     pax          pax_len bytes (only present if pax_len > 0)
+///
+///     extent_count * {
+///         tag          u8       0 = hole, 1 = data
+///         len          u64 LE
+///         file_offset  u64 LE   only present if tag == 1
+///     }
+/// }
+/// ```
+const MAGIC: &[u8; 4] = b"rtix";
+const VERSION: u32 = 2;
+
+/// One content extent of a persisted entry: either a run of real file bytes
+/// at `file_offset`, or a hole to be emitted as zeroes - mirrors
+/// `sparse::Extent`, but kept independent of it since a persisted index may
+/// be reloaded without ever touching `sparse` again.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Extent {
+    Hole { len: u64 },
+    Data { file_offset: u64, len: u64 },
+}
+
+#[derive(Debug)]
+pub struct Entry {
+    pub rel_path: PathBuf,
+    pub offset: u64,
+    pub header: [u8; 512],
+    pub pax: Vec<u8>,
+    pub extents: Vec<Extent>,
+}
+
+pub fn write_to<W: Write>(mut w: W, entries: &[Entry], total_size: u64) -> Result<(), io::Error> {
+    w.write_all(MAGIC)?;
+    w.write_all(&VERSION.to_le_bytes())?;
+    w.write_all(&int::usize_to_u64(entries.len()).to_le_bytes())?;
+    w.write_all(&total_size.to_le_bytes())?;
+
+    let max_path_len = entries.iter()
+        .map(|entry| path_bytes(entry).len())
+        .max()
+        .unwrap_or(0);
+
+    let width = path_len_width(max_path_len);
+    w.write_all(&[width])?;
+
+    for entry in entries {
+        w.write_all(&entry.offset.to_le_bytes())?;
+        w.write_all(&entry.header)?;
+        w.write_all(&int::usize_to_u64(entry.pax.len()).to_le_bytes())?;
+        w.write_all(&int::usize_to_u64(entry.extents.len()).to_le_bytes())?;
+    }
+
+    for entry in entries {
+        let path = path_bytes(entry);
+
+        write_width(&mut w, width, int::usize_to_u64(path.len()))?;
+        w.write_all(&path)?;
+        w.write_all(&entry.pax)?;
+
+        for extent in &entry.extents {
+            match extent {
+                Extent::Hole { len } => {
+                    w.write_all(&[0])?;
+                    w.write_all(&len.to_le_bytes())?;
+                }
+                Extent::Data { file_offset, len } => {
+                    w.write_all(&[1])?;
+                    w.write_all(&len.to_le_bytes())?;
+                    w.write_all(&file_offset.to_le_bytes())?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn load_from<R: Read>(mut r: R) -> Result<(u64, Vec<Entry>), io::Error> {
+    let mut magic = [0u8; 4];
+    r.read_exact(&mut magic)?;
+
+    if &magic != MAGIC {
+        return Err(io::Error::new(ErrorKind::InvalidData, "not a rangetar index"));
+    }
+
+    let version = read_u32(&mut r)?;
+
+    if version != VERSION {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("unsupported rangetar index version {}", version),
+        ));
+    }
+
+    let entry_count = read_u64(&mut r)?;
+    let total_size = read_u64(&mut r)?;
+
+    let mut width = [0u8; 1];
+    r.read_exact(&mut width)?;
+    let width = width[0];
+
+    struct FixedRecord {
+        offset: u64,
+        header: [u8; 512],
+        pax_len: u64,
+        extent_count: u64,
+    }
+
+    let mut fixed = Vec::with_capacity(usize::try_from(entry_count).unwrap_or(0));
+
+    for _ in 0..entry_count {
+        let offset = read_u64(&mut r)?;
+
+        let mut header = [0u8; 512];
+        r.read_exact(&mut header)?;
+
+        let pax_len = read_u64(&mut r)?;
+        let extent_count = read_u64(&mut r)?;
+
+        fixed.push(FixedRecord { offset, header, pax_len, extent_count });
+    }
+
+    let mut entries = Vec::with_capacity(fixed.len());
+
+    for record in fixed {
+        let path_len = read_width(&mut r, width)?;
+        let path_len = usize::try_from(path_len)
+            .map_err(|_| io::Error::new(ErrorKind::InvalidData, "path length overflows usize"))?;
+
+        let mut path_bytes = vec![0u8; path_len];
+        r.read_exact(&mut path_bytes)?;
+        let rel_path = path_from_bytes(path_bytes);
+
+        let pax_len = usize::try_from(record.pax_len)
+            .map_err(|_| io::Error::new(ErrorKind::InvalidData, "pax length overflows usize"))?;
+
+        let mut pax = vec![0u8; pax_len];
+        r.read_exact(&mut pax)?;
+
+        let mut extents = Vec::with_capacity(usize::try_from(record.extent_count).unwrap_or(0));
+
+        for _ in 0..record.extent_count {
+            let mut tag = [0u8; 1];
+            r.read_exact(&mut tag)?;
+
+            let len = read_u64(&mut r)?;
+
+            let extent = match tag[0] {
+                0 => Extent::Hole { len },
+                1 => Extent::Data { file_offset: read_u64(&mut r)?, len },
+                tag => return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("unknown extent tag {}", tag),
+                )),
+            };
+
+            extents.push(extent);
+        }
+
+        entries.push(Entry {
+            rel_path,
+            offset: record.offset,
+            header: record.header,
+            pax,
+            extents,
+        });
+    }
+
+    Ok((total_size, entries))
+}
+
+fn path_bytes(entry: &Entry) -> Vec<u8> {
+    path_to_bytes(&entry.rel_path)
+}
+
+// non-UTF-8 filenames are legal on Unix, and `tar::Header::set_path` already
+// round-trips them correctly via raw `OsStr` bytes - going through `String`
+// here would silently mangle them into U+FFFD and have `disk_path` point at
+// a different (likely nonexistent) path after reload. mirrors the
+// unix/windows split already used for positional_read: Unix paths are an
+// arbitrary byte sequence, so the round trip is lossless; Windows paths are
+// UTF-16 under the hood and have no raw-byte representation, so the best
+// available there is a lossy string.
+#[cfg(unix)]
+fn path_to_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(windows)]
+fn path_to_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+
+    PathBuf::from(OsString::from_vec(bytes))
+}
+
+#[cfg(windows)]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn path_len_width(max_len: usize) -> u8 {
+    if max_len <= u8::MAX as usize {
+        1
+    } else if max_len <= u16::MAX as usize {
+        2
+    } else if max_len <= u32::MAX as usize {
+        4
+    } else {
+        8
+    }
+}
+
+fn write_width<W: Write>(w: &mut W, width: u8, value: u64) -> Result<(), io::Error> {
+    match width {
+        1 => w.write_all(&[value as u8]),
+        2 => w.write_all(&(value as u16).to_le_bytes()),
+        4 => w.write_all(&(value as u32).to_le_bytes()),
+        8 => w.write_all(&value.to_le_bytes()),
+        _ => unreachable!("path_len_width only ever returns 1, 2, 4 or 8"),
+    }
+}
+
+fn read_width<R: Read>(r: &mut R, width: u8) -> Result<u64, io::Error> {
+    match width {
+        1 => { let mut b = [0u8; 1]; r.read_exact(&mut b)?; Ok(b[0] as u64) }
+        2 => { let mut b = [0u8; 2]; r.read_exact(&mut b)?; Ok(u16::from_le_bytes(b) as u64) }
+        4 => { let mut b = [0u8; 4]; r.read_exact(&mut b)?; Ok(u32::from_le_bytes(b) as u64) }
+        8 => { let mut b = [0u8; 8]; r.read_exact(&mut b)?; Ok(u64::from_le_bytes(b)) }
+        _ => Err(io::Error::new(ErrorKind::InvalidData, "invalid path length width")),
+    }
+}
+
+fn read_u32<R: Read>(r: &mut R) -> Result<u32, io::Error> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(r: &mut R) -> Result<u64, io::Error> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
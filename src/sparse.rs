@@ -0,0 +1,81 @@
+use std::fs::File;
+use std::io;
+
+/// A contiguous run of a file's logical content: either real bytes backed by
+/// the file at `offset`, or a hole that should be emitted as zeroes without
+/// touching disk.
+#[derive(Debug)]
+pub enum Extent {
+    Data { offset: u64, len: u64 },
+    Hole { len: u64 },
+}
+
+/// Splits `file` (whose logical size is `size`) into alternating data and
+/// hole extents using `SEEK_DATA`/`SEEK_HOLE`. Falls back to a single `Data`
+/// extent covering the whole file when the platform or filesystem doesn't
+/// support those seek whences.
+pub fn extents(file: &File, size: u64) -> Vec<Extent> {
+    scan(file, size).unwrap_or_else(|| vec![Extent::Data { offset: 0, len: size }])
+}
+
+#[cfg(unix)]
+fn scan(file: &File, size: u64) -> Option<Vec<Extent>> {
+    use std::cmp;
+    use std::os::unix::io::AsRawFd;
+
+    if size == 0 {
+        return Some(Vec::new());
+    }
+
+    let fd = file.as_raw_fd();
+    let mut extents = Vec::new();
+    let mut pos = 0u64;
+
+    loop {
+        if pos >= size {
+            break;
+        }
+
+        let data_start = match lseek(fd, pos, libc::SEEK_DATA) {
+            Ok(offset) => offset,
+            Err(libc::ENXIO) => {
+                // no more data between `pos` and EOF
+                extents.push(Extent::Hole { len: size - pos });
+                break;
+            }
+            Err(_) => return None, // SEEK_DATA unsupported on this fd/filesystem
+        };
+
+        if data_start > pos {
+            extents.push(Extent::Hole { len: data_start - pos });
+        }
+
+        let hole_start = match lseek(fd, data_start, libc::SEEK_HOLE) {
+            Ok(offset) => cmp::min(offset, size),
+            Err(_) => size,
+        };
+
+        extents.push(Extent::Data { offset: data_start, len: hole_start - data_start });
+
+        pos = hole_start;
+    }
+
+    Some(extents)
+}
+
+#[cfg(unix)]
+fn lseek(fd: std::os::unix::io::RawFd, offset: u64, whence: libc::c_int) -> Result<u64, i32> {
+    let offset = i64::try_from(offset).unwrap_or(i64::MAX);
+    let result = unsafe { libc::lseek(fd, offset, whence) };
+
+    if result < 0 {
+        Err(io::Error::last_os_error().raw_os_error().unwrap_or(0))
+    } else {
+        Ok(result as u64)
+    }
+}
+
+#[cfg(not(unix))]
+fn scan(_file: &File, _size: u64) -> Option<Vec<Extent>> {
+    None
+}
@@ -0,0 +1,58 @@
+use std::io;
+use std::path::Path;
+
+/// Reads `path`'s extended attributes as `(keyword, value)` pairs ready to
+/// be spliced into a PAX extension under the `SCHILY.xattr.<name>` keyword
+/// `tar`(1) itself uses - there's no feature flag to gate this behind here,
+/// so it's always compiled in. It returns nothing on non-Unix platforms; on
+/// a Unix filesystem without xattr support it returns the error from
+/// listing the attributes.
+///
+/// `attr(5)` values are arbitrary bytes, but PAX records are built from
+/// `String`s here, so binary values are lossily encoded - see
+/// `encode_value`.
+#[cfg(unix)]
+pub fn read(path: &Path) -> Result<Vec<(String, String)>, io::Error> {
+    let mut records = Vec::new();
+
+    for name in xattr::list(path)? {
+        let Some(value) = xattr::get(path, &name)? else {
+            continue;
+        };
+
+        records.push((
+            format!("SCHILY.xattr.{}", name.to_string_lossy()),
+            encode_value(&value),
+        ));
+    }
+
+    Ok(records)
+}
+
+// xattr values (e.g. a security.capability set) are arbitrary bytes per
+// attr(5), with no guarantee of being valid UTF-8, but the PAX record
+// plumbing only carries Strings. Rather than fail the whole scan or thread
+// raw bytes through pax::build, lossily substitute invalid sequences with
+// U+FFFD - this is intentional, not an oversight: a readable-but-possibly
+// mangled attribute value beats losing the whole file from the scan.
+#[cfg(unix)]
+fn encode_value(value: &[u8]) -> String {
+    String::from_utf8_lossy(value).into_owned()
+}
+
+#[cfg(not(unix))]
+pub fn read(_path: &Path) -> Result<Vec<(String, String)>, io::Error> {
+    Ok(Vec::new())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_value_replaces_invalid_utf8_instead_of_failing() {
+        let binary = [b'o', b'k', 0xFF, b'!'];
+
+        assert_eq!(encode_value(&binary), "ok\u{FFFD}!");
+    }
+}